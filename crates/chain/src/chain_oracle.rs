@@ -1,3 +1,7 @@
+use core::cell::{Cell, RefCell};
+
+use alloc::collections::BTreeMap;
+
 use crate::BlockId;
 
 /// Represents a service that tracks the blockchain.
@@ -19,3 +23,269 @@ pub trait ChainOracle {
         static_block: BlockId,
     ) -> Result<Option<bool>, Self::Error>;
 }
+
+/// The default depth below the tip past which cached answers are assumed final.
+pub const DEFAULT_ASSUME_FINAL_DEPTH: u32 = 10;
+
+/// A [`ChainOracle`] decorator that memoizes confirmation lookups.
+///
+/// [`ChainOracle`] implementations are typically backed by a remote (e.g. Electrum/Esplora) where
+/// [`is_block_in_chain`] is an expensive network round-trip. `CachedChainOracle` remembers every
+/// resolved `(block, static_block)` answer so repeated queries are served locally.
+///
+/// Answers for blocks deeper than `assume_final_depth` below the observed tip are kept
+/// indefinitely, since those blocks are assumed never to be reorged. Because a deep block's
+/// ancestry no longer depends on which (higher) tip it is queried against, positive answers for it
+/// are keyed on the queried `block` alone — so they stay reachable as the tip advances, which is
+/// where the amortization of deep round-trips comes from. Negative answers, and all answers for
+/// shallower blocks, depend on the exact `static_block` viewpoint, so they are keyed on the full
+/// `(block, static_block)` pair; the shallow ones among them are dropped whenever a query arrives
+/// from a viewpoint other than the last one observed. [`invalidate_from`] drops answers for blocks
+/// at or above a given height for explicit reorg handling.
+///
+/// [`is_block_in_chain`]: ChainOracle::is_block_in_chain
+/// [`invalidate_from`]: Self::invalidate_from
+#[derive(Debug)]
+pub struct CachedChainOracle<O> {
+    oracle: O,
+    assume_final_depth: u32,
+    /// Positive (`true`) answers for blocks assumed final, keyed on the queried `block` alone and
+    /// retained until a reorg at or below the block's height invalidates them. Only `true` is
+    /// stored here: a `false` depends on the `static_block` viewpoint and lives in `recent_cache`.
+    final_cache: RefCell<BTreeMap<BlockId, bool>>,
+    /// Answers keyed on the full `(block, static_block)` pair: every answer for a shallow block and
+    /// negative answers for deep ones. Entries whose block is shallow relative to the observed tip
+    /// are dropped whenever the viewpoint changes; the others are retained.
+    recent_cache: RefCell<BTreeMap<(BlockId, BlockId), bool>>,
+    tip: Cell<Option<BlockId>>,
+}
+
+impl<O: ChainOracle> CachedChainOracle<O> {
+    /// Wrap `oracle`, retaining answers for blocks deeper than `assume_final_depth` below the tip.
+    pub fn new(oracle: O, assume_final_depth: u32) -> Self {
+        Self {
+            oracle,
+            assume_final_depth,
+            final_cache: RefCell::new(BTreeMap::new()),
+            recent_cache: RefCell::new(BTreeMap::new()),
+            tip: Cell::new(None),
+        }
+    }
+
+    /// The wrapped oracle.
+    pub fn oracle(&self) -> &O {
+        &self.oracle
+    }
+
+    /// Consume the wrapper and return the inner oracle.
+    pub fn into_inner(self) -> O {
+        self.oracle
+    }
+
+    /// The depth below the tip past which cached answers are retained indefinitely.
+    pub fn assume_final_depth(&self) -> u32 {
+        self.assume_final_depth
+    }
+
+    /// Drop every cached answer for a `block` at or above `height`.
+    ///
+    /// Call this when a reorg is known to have replaced blocks from `height` upwards. Invalidation
+    /// keys off the queried `block`'s height — the thing whose inclusion is in question — not the
+    /// caller's viewpoint.
+    pub fn invalidate_from(&self, height: u32) {
+        self.final_cache
+            .borrow_mut()
+            .retain(|block, _| block.height < height);
+        self.recent_cache
+            .borrow_mut()
+            .retain(|(block, _), _| block.height < height);
+    }
+
+    /// Record the latest observed tip, dropping shallow pair-keyed answers if the viewpoint changed.
+    ///
+    /// The finalized cache is left untouched — those answers are independent of the tip.
+    fn observe_tip(&self, static_block: BlockId) {
+        // Already the current viewpoint: nothing changed, so don't thrash the cache.
+        if self.tip.get() == Some(static_block) {
+            return;
+        }
+        // Keep the highest tip seen, but on a same-height reorg adopt the new hash so the stored
+        // tip actually advances — otherwise every later call would see a mismatch and re-clear.
+        let tip = match self.tip.get() {
+            Some(prev) if prev.height > static_block.height => prev,
+            _ => static_block,
+        };
+        self.tip.set(Some(tip));
+        // Answers for blocks that are deep relative to the new tip are assumed final and kept.
+        let boundary = self.final_boundary(tip);
+        self.recent_cache
+            .borrow_mut()
+            .retain(|(block, _), _| block.height <= boundary);
+    }
+
+    /// The depth boundary below which a queried block is assumed final, given the observed tip.
+    fn final_boundary(&self, static_block: BlockId) -> u32 {
+        let tip_height = self
+            .tip
+            .get()
+            .map_or(static_block.height, |tip| tip.height.max(static_block.height));
+        tip_height.saturating_sub(self.assume_final_depth)
+    }
+}
+
+impl<O: ChainOracle> ChainOracle for CachedChainOracle<O> {
+    type Error = O::Error;
+
+    fn is_block_in_chain(
+        &self,
+        block: BlockId,
+        static_block: BlockId,
+    ) -> Result<Option<bool>, Self::Error> {
+        self.observe_tip(static_block);
+
+        if let Some(&answer) = self.final_cache.borrow().get(&block) {
+            return Ok(Some(answer));
+        }
+        if let Some(&answer) = self.recent_cache.borrow().get(&(block, static_block)) {
+            return Ok(Some(answer));
+        }
+
+        let result = self.oracle.is_block_in_chain(block, static_block)?;
+        if let Some(answer) = result {
+            // Only a `true` answer for a deep block is keyed on `block` alone: such a block is an
+            // ancestor of every future tip, so the answer holds regardless of viewpoint. A `false`
+            // may have stemmed from an off-chain `static_block`, so it stays keyed on the full pair
+            // lest it be wrongly served for a later valid viewpoint.
+            if answer && block.height <= self.final_boundary(static_block) {
+                self.final_cache.borrow_mut().insert(block, answer);
+            } else {
+                self.recent_cache
+                    .borrow_mut()
+                    .insert((block, static_block), answer);
+            }
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::cell::Cell;
+
+    use bitcoin::hashes::Hash;
+    use bitcoin::BlockHash;
+
+    fn block(height: u32, seed: u8) -> BlockId {
+        BlockId {
+            height,
+            hash: BlockHash::from_byte_array([seed; 32]),
+        }
+    }
+
+    /// An oracle that always answers `true` and counts how often it is consulted.
+    struct CountingOracle {
+        calls: Cell<usize>,
+    }
+
+    impl ChainOracle for CountingOracle {
+        type Error = core::convert::Infallible;
+
+        fn is_block_in_chain(
+            &self,
+            _block: BlockId,
+            _static_block: BlockId,
+        ) -> Result<Option<bool>, Self::Error> {
+            self.calls.set(self.calls.get() + 1);
+            Ok(Some(true))
+        }
+    }
+
+    #[test]
+    fn deep_answers_survive_tip_advance() {
+        let oracle = CachedChainOracle::new(CountingOracle { calls: Cell::new(0) }, 10);
+        let deep = block(5, 5);
+
+        // resolve a deep block against an early tip — this hits the oracle once
+        assert_eq!(oracle.is_block_in_chain(deep, block(100, 100)), Ok(Some(true)));
+        assert_eq!(oracle.oracle().calls.get(), 1);
+
+        // advance the tip; the finalized answer must still be served from cache
+        assert_eq!(oracle.is_block_in_chain(deep, block(200, 200)), Ok(Some(true)));
+        assert_eq!(oracle.oracle().calls.get(), 1);
+
+        // a shallow block is not served from the finalized cache, so its query hits the oracle
+        let shallow = block(195, 1);
+        assert_eq!(oracle.is_block_in_chain(shallow, block(200, 200)), Ok(Some(true)));
+        assert_eq!(oracle.oracle().calls.get(), 2);
+    }
+
+    #[test]
+    fn invalidate_from_keys_on_block_height() {
+        let oracle = CachedChainOracle::new(CountingOracle { calls: Cell::new(0) }, 10);
+        let deep = block(5, 5);
+        assert_eq!(oracle.is_block_in_chain(deep, block(100, 100)), Ok(Some(true)));
+        assert_eq!(oracle.oracle().calls.get(), 1);
+
+        // invalidating above the block's height leaves it cached
+        oracle.invalidate_from(6);
+        assert_eq!(oracle.is_block_in_chain(deep, block(100, 100)), Ok(Some(true)));
+        assert_eq!(oracle.oracle().calls.get(), 1);
+
+        // invalidating at or below the block's height drops it
+        oracle.invalidate_from(5);
+        assert_eq!(oracle.is_block_in_chain(deep, block(100, 100)), Ok(Some(true)));
+        assert_eq!(oracle.oracle().calls.get(), 2);
+    }
+
+    #[test]
+    fn same_height_reorg_updates_tip_without_thrash() {
+        let oracle = CachedChainOracle::new(CountingOracle { calls: Cell::new(0) }, 10);
+        let shallow = block(95, 1);
+
+        // establish viewpoint A
+        assert_eq!(oracle.is_block_in_chain(shallow, block(100, 100)), Ok(Some(true)));
+        assert_eq!(oracle.oracle().calls.get(), 1);
+
+        // a same-height reorg to viewpoint B drops the shallow cache — one refetch
+        assert_eq!(oracle.is_block_in_chain(shallow, block(100, 200)), Ok(Some(true)));
+        assert_eq!(oracle.oracle().calls.get(), 2);
+
+        // viewpoint B is now the stored tip, so repeated queries are served from cache rather than
+        // thrashing (which is what the stale-tip bug caused).
+        assert_eq!(oracle.is_block_in_chain(shallow, block(100, 200)), Ok(Some(true)));
+        assert_eq!(oracle.oracle().calls.get(), 2);
+    }
+
+    /// An oracle that reports ancestry for every viewpoint except the "wrong fork" (hash seed 200).
+    struct ForkAwareOracle {
+        calls: Cell<usize>,
+    }
+
+    impl ChainOracle for ForkAwareOracle {
+        type Error = core::convert::Infallible;
+
+        fn is_block_in_chain(
+            &self,
+            _block: BlockId,
+            static_block: BlockId,
+        ) -> Result<Option<bool>, Self::Error> {
+            self.calls.set(self.calls.get() + 1);
+            Ok(Some(static_block != block(static_block.height, 200)))
+        }
+    }
+
+    #[test]
+    fn negative_answers_are_not_served_across_viewpoints() {
+        let oracle = CachedChainOracle::new(ForkAwareOracle { calls: Cell::new(0) }, 10);
+        let deep = block(5, 5);
+
+        // a `false` from an off-chain viewpoint must not be cached block-only
+        assert_eq!(oracle.is_block_in_chain(deep, block(100, 200)), Ok(Some(false)));
+        assert_eq!(oracle.oracle().calls.get(), 1);
+
+        // a later valid viewpoint re-queries and gets the correct `true`
+        assert_eq!(oracle.is_block_in_chain(deep, block(100, 100)), Ok(Some(true)));
+        assert_eq!(oracle.oracle().calls.get(), 2);
+    }
+}
@@ -1,26 +1,220 @@
 use core::convert::Infallible;
 
 use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+use bitcoin::hashes::{sha256, Hash, HashEngine};
 use bitcoin::BlockHash;
 
 use crate::{BlockId, ChainOracle};
 
-/// This is a local implementation of [`ChainOracle`].
+/// The default number of blocks below the tip that are kept non-finalized.
 ///
-/// TODO: We need a cache/snapshot thing for chain oracle.
-/// * Minimize calls to remotes.
-/// * Can we cache it forever? Should we drop stuff?
-/// * Assume anything deeper than (i.e. 10) blocks won't be reorged.
-/// * Is this a cache on txs or block? or both?
-/// TODO: Parents of children are confirmed if children are confirmed.
+/// Anything deeper than this is assumed not to be reorged and is treated as finalized, where it
+/// can never be invalidated.
+pub const DEFAULT_REORG_DEPTH: u32 = 10;
+
+/// Backing storage for the `(height, BlockHash)` map of a [`LocalChain`].
+///
+/// Factoring storage behind this trait lets a [`LocalChain`] be backed by something other than an
+/// in-memory map — e.g. a SQLite/file-backed store, or the [`PrunedStore`] which keeps memory
+/// bounded by discarding most deep headers.
+pub trait ChainStore {
+    /// Get the block hash at `height`, if stored.
+    fn get_block_hash(&self, height: u32) -> Option<BlockHash>;
+
+    /// Store `hash` at `height`.
+    fn set_block_hash(&mut self, height: u32, hash: BlockHash);
+
+    /// Remove every block at or above `height`.
+    ///
+    /// Chain invalidation always drops a suffix, so removal is expressed as a truncation.
+    fn remove_from(&mut self, height: u32);
+
+    /// The highest stored `(height, hash)`, if any.
+    fn tip(&self) -> Option<(u32, BlockHash)>;
+
+    /// Collect the stored `(height, hash)` pairs from `height` upward, in ascending order.
+    fn iter_from(&self, height: u32) -> Vec<(u32, BlockHash)>;
+
+    /// Whether the store already accounts for `(height, hash)` and so need not have it re-written.
+    ///
+    /// The default answers `true` only for an exact stored match. A lossy store (e.g.
+    /// [`PrunedStore`]) overrides this so that a height it intentionally dropped counts as present,
+    /// keeping [`determine_changeset`] idempotent instead of re-emitting — and re-pruning — every
+    /// deep header on each sync.
+    ///
+    /// [`determine_changeset`]: LocalChain::determine_changeset
+    fn is_present(&self, height: u32, hash: BlockHash) -> bool {
+        self.get_block_hash(height) == Some(hash)
+    }
+}
+
+/// The default, in-memory [`ChainStore`] backed by a [`BTreeMap`].
 #[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub struct LocalChain {
+pub struct InMemoryStore {
     blocks: BTreeMap<u32, BlockHash>,
 }
 
-impl ChainOracle for LocalChain {
+impl From<BTreeMap<u32, BlockHash>> for InMemoryStore {
+    fn from(blocks: BTreeMap<u32, BlockHash>) -> Self {
+        Self { blocks }
+    }
+}
+
+impl ChainStore for InMemoryStore {
+    fn get_block_hash(&self, height: u32) -> Option<BlockHash> {
+        self.blocks.get(&height).copied()
+    }
+
+    fn set_block_hash(&mut self, height: u32, hash: BlockHash) {
+        self.blocks.insert(height, hash);
+    }
+
+    fn remove_from(&mut self, height: u32) {
+        let to_remove: Vec<u32> = self.blocks.range(height..).map(|(&h, _)| h).collect();
+        for height in to_remove {
+            self.blocks.remove(&height);
+        }
+    }
+
+    fn tip(&self) -> Option<(u32, BlockHash)> {
+        self.blocks.iter().next_back().map(|(&h, &hash)| (h, hash))
+    }
+
+    fn iter_from(&self, height: u32) -> Vec<(u32, BlockHash)> {
+        self.blocks.range(height..).map(|(&h, &hash)| (h, hash)).collect()
+    }
+}
+
+/// A [`ChainStore`] that keeps memory bounded by pruning most deep headers.
+///
+/// Heights within `recent_window` of the tip are retained at full resolution so that recent
+/// confirmations are always answerable. Below that window only every `checkpoint_interval`-th
+/// header is kept, so deep blocks are answered from sparse checkpoints instead of the full map.
+///
+/// Because this store is lossy, a finalized height that falls between checkpoints is not
+/// retained; [`LocalChain::is_block_in_chain`] then answers `None` for it rather than the
+/// unconditional `Some(true)` a lossless store would give (see the note on that method).
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PrunedStore {
+    blocks: BTreeMap<u32, BlockHash>,
+    recent_window: u32,
+    checkpoint_interval: u32,
+}
+
+impl PrunedStore {
+    /// Create a pruning store that keeps `recent_window` contiguous heights below the tip and,
+    /// below that, every `checkpoint_interval`-th header.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `checkpoint_interval` is zero.
+    pub fn new(recent_window: u32, checkpoint_interval: u32) -> Self {
+        assert!(checkpoint_interval > 0, "checkpoint_interval must be non-zero");
+        Self {
+            blocks: BTreeMap::new(),
+            recent_window,
+            checkpoint_interval,
+        }
+    }
+
+    fn prune(&mut self) {
+        let tip_height = match self.blocks.keys().next_back() {
+            Some(&height) => height,
+            None => return,
+        };
+        let boundary = tip_height.saturating_sub(self.recent_window);
+        let to_remove: Vec<u32> = self
+            .blocks
+            .range(..boundary)
+            .map(|(&h, _)| h)
+            .filter(|h| h % self.checkpoint_interval != 0)
+            .collect();
+        for height in to_remove {
+            self.blocks.remove(&height);
+        }
+    }
+}
+
+impl ChainStore for PrunedStore {
+    fn get_block_hash(&self, height: u32) -> Option<BlockHash> {
+        self.blocks.get(&height).copied()
+    }
+
+    fn set_block_hash(&mut self, height: u32, hash: BlockHash) {
+        self.blocks.insert(height, hash);
+        self.prune();
+    }
+
+    fn remove_from(&mut self, height: u32) {
+        let to_remove: Vec<u32> = self.blocks.range(height..).map(|(&h, _)| h).collect();
+        for height in to_remove {
+            self.blocks.remove(&height);
+        }
+    }
+
+    fn tip(&self) -> Option<(u32, BlockHash)> {
+        self.blocks.iter().next_back().map(|(&h, &hash)| (h, hash))
+    }
+
+    fn iter_from(&self, height: u32) -> Vec<(u32, BlockHash)> {
+        self.blocks.range(height..).map(|(&h, &hash)| (h, hash)).collect()
+    }
+
+    fn is_present(&self, height: u32, hash: BlockHash) -> bool {
+        match self.get_block_hash(height) {
+            // retained: answer exactly
+            Some(stored) => stored == hash,
+            // not retained: a height at or below the tip was intentionally pruned and is assumed
+            // still part of the chain, so it should not be re-written. A height above the tip is
+            // genuinely new and must be applied.
+            None => self.tip().map_or(false, |(tip, _)| height <= tip),
+        }
+    }
+}
+
+/// This is a local implementation of [`ChainOracle`].
+///
+/// Blocks are kept behind a [`ChainStore`] (the in-memory [`InMemoryStore`] by default). A
+/// finalization watermark splits the chain into two tiers inspired by Zebra's
+/// finalized/non-finalized split: everything at or below [`finalized_tip`] is assumed to never be
+/// reorged, while the most recent `reorg_depth` blocks may still be replaced. Whenever the tip
+/// advances, the watermark advances to `tip.height - reorg_depth`, and updates that would
+/// invalidate a finalized block are rejected. This bounds how far a reorg can reach and lets
+/// [`is_block_in_chain`] answer cheaply for finalized heights.
+///
+/// TODO: Parents of children are confirmed if children are confirmed.
+///
+/// [`finalized_tip`]: Self::finalized_tip
+/// [`is_block_in_chain`]: ChainOracle::is_block_in_chain
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LocalChain<S = InMemoryStore> {
+    store: S,
+    reorg_depth: u32,
+    finalized_tip: Option<u32>,
+    active_score: u128,
+    candidates: Vec<Fork>,
+}
+
+impl<S: Default> Default for LocalChain<S> {
+    fn default() -> Self {
+        Self {
+            store: S::default(),
+            reorg_depth: DEFAULT_REORG_DEPTH,
+            finalized_tip: None,
+            active_score: 0,
+            candidates: Vec::new(),
+        }
+    }
+}
+
+impl<S: ChainStore> ChainOracle for LocalChain<S> {
     type Error = Infallible;
 
+    // Note: the cheap `Some(true)` guarantee for finalized heights holds only for a lossless store.
+    // With a lossy [`ChainStore`] such as [`PrunedStore`], a finalized-but-pruned (non-checkpoint)
+    // height has no retained hash to validate the query against, so this answers `None` ("unknown")
+    // for it rather than vouching for ancestry it can no longer verify.
     fn is_block_in_chain(
         &self,
         block: BlockId,
@@ -29,12 +223,22 @@ impl ChainOracle for LocalChain {
         if block.height > static_block.height {
             return Ok(None);
         }
+        // Finalized blocks are assumed permanent, so a matching finalized hash is an ancestor of
+        // any higher block — but only once we have confirmed `static_block` is itself on this
+        // chain. Skipping that check would vouch for ancestry against a `static_block` that never
+        // belonged here.
+        if self.is_finalized(block.height)
+            && self.store.get_block_hash(block.height) == Some(block.hash)
+            && self.store.get_block_hash(static_block.height) == Some(static_block.hash)
+        {
+            return Ok(Some(true));
+        }
         Ok(
             match (
-                self.blocks.get(&block.height),
-                self.blocks.get(&static_block.height),
+                self.store.get_block_hash(block.height),
+                self.store.get_block_hash(static_block.height),
             ) {
-                (Some(&hash), Some(&static_hash)) => {
+                (Some(hash), Some(static_hash)) => {
                     Some(hash == block.hash && static_hash == static_block.hash)
                 }
                 _ => None,
@@ -43,54 +247,101 @@ impl ChainOracle for LocalChain {
     }
 }
 
-impl AsRef<BTreeMap<u32, BlockHash>> for LocalChain {
-    fn as_ref(&self) -> &BTreeMap<u32, BlockHash> {
-        &self.blocks
+impl From<BTreeMap<u32, BlockHash>> for LocalChain<InMemoryStore> {
+    fn from(value: BTreeMap<u32, BlockHash>) -> Self {
+        Self::from_store(InMemoryStore::from(value))
     }
 }
 
-impl From<LocalChain> for BTreeMap<u32, BlockHash> {
-    fn from(value: LocalChain) -> Self {
-        value.blocks
+impl AsRef<BTreeMap<u32, BlockHash>> for LocalChain<InMemoryStore> {
+    fn as_ref(&self) -> &BTreeMap<u32, BlockHash> {
+        &self.store.blocks
     }
 }
 
-impl From<BTreeMap<u32, BlockHash>> for LocalChain {
-    fn from(value: BTreeMap<u32, BlockHash>) -> Self {
-        Self { blocks: value }
+impl From<LocalChain<InMemoryStore>> for BTreeMap<u32, BlockHash> {
+    fn from(value: LocalChain<InMemoryStore>) -> Self {
+        value.store.blocks
     }
 }
 
-impl LocalChain {
+impl LocalChain<InMemoryStore> {
     pub fn from_blocks<B>(blocks: B) -> Self
     where
         B: IntoIterator<Item = BlockId>,
     {
-        Self {
-            blocks: blocks.into_iter().map(|b| (b.height, b.hash)).collect(),
-        }
+        let blocks = blocks.into_iter().map(|b| (b.height, b.hash)).collect();
+        Self::from_store(InMemoryStore { blocks })
+    }
+}
+
+impl<S: ChainStore> LocalChain<S> {
+    /// Build a chain from an existing [`ChainStore`], establishing the finalization watermark.
+    ///
+    /// There is no `Default` bound on `S`, so stores that cannot be default-constructed (e.g.
+    /// [`PrunedStore`]) can still back a [`LocalChain`]. [`from_store`] is equivalent.
+    ///
+    /// [`from_store`]: Self::from_store
+    pub fn new(store: S) -> Self {
+        let mut chain = Self {
+            store,
+            reorg_depth: DEFAULT_REORG_DEPTH,
+            finalized_tip: None,
+            active_score: 0,
+            candidates: Vec::new(),
+        };
+        chain.finalize();
+        chain
+    }
+
+    /// Build a chain from an existing [`ChainStore`], establishing the finalization watermark.
+    pub fn from_store(store: S) -> Self {
+        Self::new(store)
+    }
+
+    /// The backing store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// The number of blocks below the tip that are kept non-finalized.
+    pub fn reorg_depth(&self) -> u32 {
+        self.reorg_depth
+    }
+
+    /// The highest finalized height, if the chain is deep enough for any block to be finalized.
+    pub fn finalized_tip(&self) -> Option<u32> {
+        self.finalized_tip
+    }
+
+    /// Whether the block at `height` is finalized.
+    pub fn is_finalized(&self, height: u32) -> bool {
+        self.finalized_tip.map_or(false, |finalized| height <= finalized)
     }
 
     pub fn tip(&self) -> Option<BlockId> {
-        self.blocks
-            .iter()
-            .last()
-            .map(|(&height, &hash)| BlockId { height, hash })
+        self.store.tip().map(|(height, hash)| BlockId { height, hash })
     }
 
     /// Get a block at the given height.
     pub fn get_block(&self, height: u32) -> Option<BlockId> {
-        self.blocks
-            .get(&height)
-            .map(|&hash| BlockId { height, hash })
+        self.store
+            .get_block_hash(height)
+            .map(|hash| BlockId { height, hash })
     }
 
     /// This is like the sparsechain's logic, expect we must guarantee that all invalidated heights
     /// are to be re-filled.
-    pub fn determine_changeset(&self, update: &Self) -> Result<ChangeSet, UpdateNotConnectedError> {
-        let update = update.as_ref();
-        let update_tip = match update.keys().last().cloned() {
-            Some(tip) => tip,
+    ///
+    /// An update that would invalidate a finalized block is rejected with
+    /// [`ApplyUpdateError::FinalizedReorg`].
+    pub fn determine_changeset<U: ChainStore>(
+        &self,
+        update: &LocalChain<U>,
+    ) -> Result<ChangeSet, ApplyUpdateError> {
+        let update = update.store.iter_from(0);
+        let update_tip = match update.last() {
+            Some(&(height, _)) => height,
             None => return Ok(ChangeSet::default()),
         };
 
@@ -98,8 +349,8 @@ impl LocalChain {
         let agreement_height = update
             .iter()
             .rev()
-            .find(|&(u_height, u_hash)| self.blocks.get(u_height) == Some(u_hash))
-            .map(|(&height, _)| height);
+            .find(|&&(height, hash)| self.store.get_block_hash(height) == Some(hash))
+            .map(|&(height, _)| height);
 
         // the lower bound of the range to invalidate
         let invalidate_lb = match agreement_height {
@@ -109,32 +360,33 @@ impl LocalChain {
         };
 
         // the first block's height to invalidate in the local chain
-        let invalidate_from_height = self.blocks.range(invalidate_lb..).next().map(|(&h, _)| h);
+        let invalidate_from_height = self.store.iter_from(invalidate_lb).first().map(|&(h, _)| h);
 
-        // the first block of height to invalidate (if any) should be represented in the update
+        // the first block of height to invalidate (if any) should be represented in the update, and
+        // must not reach into the finalized segment
         if let Some(first_invalid_height) = invalidate_from_height {
-            if !update.contains_key(&first_invalid_height) {
-                return Err(UpdateNotConnectedError(first_invalid_height));
+            if let Some(finalized_tip) = self.finalized_tip {
+                if first_invalid_height <= finalized_tip {
+                    return Err(ApplyUpdateError::FinalizedReorg(first_invalid_height));
+                }
+            }
+            if !update.iter().any(|&(height, _)| height == first_invalid_height) {
+                return Err(UpdateNotConnectedError(first_invalid_height).into());
             }
         }
 
         let mut changeset: BTreeMap<u32, Option<BlockHash>> = match invalidate_from_height {
-            Some(first_invalid_height) => {
-                // the first block of height to invalidate should be represented in the update
-                if !update.contains_key(&first_invalid_height) {
-                    return Err(UpdateNotConnectedError(first_invalid_height));
-                }
-                self.blocks
-                    .range(first_invalid_height..)
-                    .map(|(height, _)| (*height, None))
-                    .collect()
-            }
+            Some(first_invalid_height) => self
+                .store
+                .iter_from(first_invalid_height)
+                .into_iter()
+                .map(|(height, _)| (height, None))
+                .collect(),
             None => BTreeMap::new(),
         };
         for (height, update_hash) in update {
-            let original_hash = self.blocks.get(height);
-            if Some(update_hash) != original_hash {
-                changeset.insert(*height, Some(*update_hash));
+            if !self.store.is_present(height, update_hash) {
+                changeset.insert(height, Some(update_hash));
             }
         }
 
@@ -142,13 +394,19 @@ impl LocalChain {
     }
 
     /// Applies the given `changeset`.
+    ///
+    /// Any invalidated suffix is truncated from the store first, then the new blocks are written
+    /// and the finalization watermark is advanced.
     pub fn apply_changeset(&mut self, changeset: ChangeSet) {
-        for (height, blockhash) in changeset {
-            match blockhash {
-                Some(blockhash) => self.blocks.insert(height, blockhash),
-                None => self.blocks.remove(&height),
-            };
+        if let Some((&first_removed, _)) = changeset.iter().find(|(_, hash)| hash.is_none()) {
+            self.store.remove_from(first_removed);
+        }
+        for (height, blockhash) in &changeset {
+            if let Some(hash) = blockhash {
+                self.store.set_block_hash(*height, *hash);
+            }
         }
+        self.finalize();
     }
 
     /// Updates [`LocalChain`] with an update [`LocalChain`].
@@ -157,21 +415,437 @@ impl LocalChain {
     ///
     /// [`determine_changeset`]: Self::determine_changeset
     /// [`apply_changeset`]: Self::apply_changeset
-    pub fn apply_update(&mut self, update: Self) -> Result<ChangeSet, UpdateNotConnectedError> {
+    pub fn apply_update<U: ChainStore>(
+        &mut self,
+        update: LocalChain<U>,
+    ) -> Result<ChangeSet, ApplyUpdateError> {
         let changeset = self.determine_changeset(&update)?;
         self.apply_changeset(changeset.clone());
         Ok(changeset)
     }
 
     pub fn initial_changeset(&self) -> ChangeSet {
-        self.blocks
-            .iter()
-            .map(|(&height, &hash)| (height, Some(hash)))
+        self.store
+            .iter_from(0)
+            .into_iter()
+            .map(|(height, hash)| (height, Some(hash)))
             .collect()
     }
 
     pub fn heights(&self) -> BTreeSet<u32> {
-        self.blocks.keys().cloned().collect()
+        self.store.iter_from(0).into_iter().map(|(height, _)| height).collect()
+    }
+
+    /// Advance the finalization watermark to `tip.height - reorg_depth`.
+    fn finalize(&mut self) {
+        if let Some((tip_height, _)) = self.store.tip() {
+            if let Some(boundary) = tip_height.checked_sub(self.reorg_depth) {
+                if self.finalized_tip.map_or(true, |finalized| boundary > finalized) {
+                    self.finalized_tip = Some(boundary);
+                }
+            }
+        }
+    }
+
+    /// Cumulative work/score of the currently committed chain.
+    pub fn active_score(&self) -> u128 {
+        self.active_score
+    }
+
+    /// Set the cumulative work/score of the currently committed chain.
+    ///
+    /// This is the baseline that candidate forks are compared against in [`best_tip`] and
+    /// [`commit_best_chain`].
+    ///
+    /// [`best_tip`]: Self::best_tip
+    /// [`commit_best_chain`]: Self::commit_best_chain
+    pub fn set_active_score(&mut self, score: u128) {
+        self.active_score = score;
+    }
+
+    /// The competing forks currently being tracked.
+    pub fn candidates(&self) -> &[Fork] {
+        &self.candidates
+    }
+
+    /// Stage a competing fork so that it can be weighed against the active chain.
+    ///
+    /// The fork is not applied until [`commit_best_chain`] is called.
+    ///
+    /// [`commit_best_chain`]: Self::commit_best_chain
+    pub fn add_candidate(&mut self, fork: Fork) {
+        self.candidates.push(fork);
+    }
+
+    /// The tip of the highest-scoring chain currently known, considering both the committed chain
+    /// and every staged candidate fork.
+    ///
+    /// The active chain wins ties, so a fork only becomes "best" if it carries strictly more work.
+    pub fn best_tip(&self) -> Option<BlockId> {
+        let active = self.tip().map(|tip| (self.active_score, tip));
+        let best_candidate = self
+            .candidates
+            .iter()
+            .filter_map(|fork| fork.tip().map(|tip| (fork.score, tip)))
+            .max_by_key(|(score, _)| *score);
+        match (active, best_candidate) {
+            (Some((active_score, active_tip)), Some((candidate_score, candidate_tip))) => {
+                Some(if candidate_score > active_score {
+                    candidate_tip
+                } else {
+                    active_tip
+                })
+            }
+            (Some((_, tip)), None) | (None, Some((_, tip))) => Some(tip),
+            (None, None) => None,
+        }
+    }
+
+    /// The root of the Merkle tree built over the blocks map.
+    ///
+    /// Each leaf is `hash(LEAF_TAG || height || block_hash)` in ascending height order; internal
+    /// nodes are `hash(NODE_TAG || left || right)` (the last node is duplicated when a level has an
+    /// odd count). The distinct leaf/node tags domain-separate the two so a leaf can never be
+    /// passed off as an internal node to an untrusted verifier. Returns
+    /// `None` if the chain is empty. A verifier holding a trusted root can check individual
+    /// confirmations with [`verify_block_proof`] without downloading the whole map.
+    pub fn header_tree_root(&self) -> Option<sha256::Hash> {
+        let mut level: Vec<sha256::Hash> = self
+            .store
+            .iter_from(0)
+            .iter()
+            .map(|(height, hash)| leaf_hash(*height, hash))
+            .collect();
+        if level.is_empty() {
+            return None;
+        }
+        while level.len() > 1 {
+            level = merkle_level(&level);
+        }
+        Some(level[0])
+    }
+
+    /// Produce a compact inclusion proof that the block at `height` is part of this chain's
+    /// [`header_tree_root`].
+    ///
+    /// Returns `None` if no block is stored at `height`.
+    ///
+    /// [`header_tree_root`]: Self::header_tree_root
+    pub fn prove_block(&self, height: u32) -> Option<InclusionProof> {
+        let blocks = self.store.iter_from(0);
+        let index = blocks.iter().position(|(h, _)| *h == height)?;
+        let (block_height, block_hash) = blocks[index];
+
+        let mut level: Vec<sha256::Hash> = blocks
+            .iter()
+            .map(|(h, hash)| leaf_hash(*h, hash))
+            .collect();
+        let mut idx = index;
+        let mut siblings = Vec::new();
+        while level.len() > 1 {
+            if level.len() % 2 == 1 {
+                let last = level[level.len() - 1];
+                level.push(last);
+            }
+            siblings.push(level[idx ^ 1]);
+            level = merkle_level(&level);
+            idx /= 2;
+        }
+
+        Some(InclusionProof {
+            block: BlockId {
+                height: block_height,
+                hash: block_hash,
+            },
+            index,
+            siblings,
+        })
+    }
+}
+
+impl<S: ChainStore + Default> LocalChain<S> {
+    /// Switch the active chain to the highest-scoring candidate fork and return the [`ChangeSet`]
+    /// that effects the switch.
+    ///
+    /// If no candidate carries strictly more work than the active chain, the active chain is kept
+    /// and an empty [`ChangeSet`] is returned. Either way the staged candidates are cleared.
+    pub fn commit_best_chain(&mut self) -> Result<ChangeSet, ApplyUpdateError> {
+        let best = self
+            .candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, fork)| fork.score > self.active_score)
+            .max_by_key(|(_, fork)| fork.score)
+            .map(|(index, _)| index);
+        let fork = match best {
+            Some(index) => self.candidates.remove(index),
+            None => {
+                self.candidates.clear();
+                return Ok(ChangeSet::default());
+            }
+        };
+
+        // the update keeps the active chain up to (and including) the divergence point, then
+        // replaces everything above it with the fork's blocks
+        let mut store = S::default();
+        for (height, hash) in self.store.iter_from(0) {
+            if height <= fork.divergence {
+                store.set_block_hash(height, hash);
+            }
+        }
+        for (&height, &hash) in &fork.blocks {
+            store.set_block_hash(height, hash);
+        }
+        let update = LocalChain {
+            store,
+            reorg_depth: self.reorg_depth,
+            ..Default::default()
+        };
+
+        let changeset = self.determine_changeset(&update)?;
+        self.apply_changeset(changeset.clone());
+        self.active_score = fork.score;
+        self.candidates.clear();
+        Ok(changeset)
+    }
+}
+
+/// A competing fork staged against a [`LocalChain`].
+///
+/// A fork shares the active chain's history up to (and including) its [`divergence`] height and
+/// supplies its own blocks above that point, along with the cumulative work/score of its tip so
+/// that [`LocalChain`] can pick the best chain deterministically.
+///
+/// [`divergence`]: Self::divergence
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Fork {
+    /// The height at which this fork diverges from the active chain. Blocks at or below this
+    /// height are shared with the active chain.
+    pub divergence: u32,
+    /// The fork's own blocks, above the divergence point.
+    pub blocks: BTreeMap<u32, BlockHash>,
+    /// The cumulative work/score of this fork's tip.
+    pub score: u128,
+}
+
+impl Fork {
+    /// Create a fork diverging at `divergence` from `blocks`, carrying the given `score`.
+    pub fn from_blocks<B>(divergence: u32, score: u128, blocks: B) -> Self
+    where
+        B: IntoIterator<Item = BlockId>,
+    {
+        Self {
+            divergence,
+            score,
+            blocks: blocks.into_iter().map(|b| (b.height, b.hash)).collect(),
+        }
+    }
+
+    /// The tip of this fork, if it has any blocks.
+    pub fn tip(&self) -> Option<BlockId> {
+        self.blocks
+            .iter()
+            .next_back()
+            .map(|(&height, &hash)| BlockId { height, hash })
+    }
+}
+
+/// A compact Merkle proof that a given [`BlockId`] is a leaf of a [`header_tree_root`].
+///
+/// [`header_tree_root`]: LocalChain::header_tree_root
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InclusionProof {
+    /// The block being proven.
+    pub block: BlockId,
+    /// The leaf's index among the ordered leaves, used to recover the hashing order.
+    pub index: usize,
+    /// The sibling hashes along the path from the leaf up to the root.
+    pub siblings: Vec<sha256::Hash>,
+}
+
+/// Verify an [`InclusionProof`] against a trusted `root`.
+///
+/// Recomputes the Merkle path from the leaf up and checks that it reaches `root`. Returns `false`
+/// if the proof is for a different block than `block`.
+pub fn verify_block_proof(root: sha256::Hash, proof: &InclusionProof, block: BlockId) -> bool {
+    if proof.block != block {
+        return false;
+    }
+    let mut node = leaf_hash(block.height, &block.hash);
+    let mut idx = proof.index;
+    for &sibling in &proof.siblings {
+        node = if idx % 2 == 0 {
+            merkle_parent(node, sibling)
+        } else {
+            merkle_parent(sibling, node)
+        };
+        idx /= 2;
+    }
+    node == root
+}
+
+/// Domain-separation tags prefixed before hashing so that a leaf can never be reinterpreted as an
+/// internal node (the second-preimage / CVE-2012-2459 shape this proof format must resist).
+const LEAF_TAG: u8 = 0x00;
+const NODE_TAG: u8 = 0x01;
+
+/// The leaf hash for a block: `hash(LEAF_TAG || height || block_hash)`.
+fn leaf_hash(height: u32, hash: &BlockHash) -> sha256::Hash {
+    let hash_bytes: &[u8] = hash.as_ref();
+    let mut engine = sha256::Hash::engine();
+    engine.input(&[LEAF_TAG]);
+    engine.input(&height.to_be_bytes());
+    engine.input(hash_bytes);
+    sha256::Hash::from_engine(engine)
+}
+
+/// Hash two child nodes into their parent: `hash(NODE_TAG || left || right)`.
+fn merkle_parent(left: sha256::Hash, right: sha256::Hash) -> sha256::Hash {
+    let left_bytes: &[u8] = left.as_ref();
+    let right_bytes: &[u8] = right.as_ref();
+    let mut engine = sha256::Hash::engine();
+    engine.input(&[NODE_TAG]);
+    engine.input(left_bytes);
+    engine.input(right_bytes);
+    sha256::Hash::from_engine(engine)
+}
+
+/// Collapse one level of the Merkle tree, duplicating the last node on an odd count.
+fn merkle_level(level: &[sha256::Hash]) -> Vec<sha256::Hash> {
+    let mut next = Vec::with_capacity((level.len() + 1) / 2);
+    for pair in level.chunks(2) {
+        let left = pair[0];
+        let right = if pair.len() == 2 { pair[1] } else { pair[0] };
+        next.push(merkle_parent(left, right));
+    }
+    next
+}
+
+/// A staging area for updates that do not yet connect to a [`LocalChain`].
+///
+/// Following Zebra's `QueuedBlocks`, updates whose suffix does not connect to the existing chain
+/// are stashed here (indexed by their lowest height) instead of being rejected, so that headers
+/// arriving out of order from concurrent sources are not lost. Whenever a connecting update
+/// arrives, [`process_queue`] replays the staged updates and applies any whose gap has since been
+/// filled, pruning entries that can no longer connect below the finalized tip.
+///
+/// [`process_queue`]: Self::process_queue
+#[derive(Debug, Clone)]
+pub struct QueuedBlocks<S = InMemoryStore> {
+    chain: LocalChain<S>,
+    queued: BTreeMap<u32, Vec<LocalChain<S>>>,
+}
+
+impl<S: Default> Default for QueuedBlocks<S> {
+    fn default() -> Self {
+        Self {
+            chain: LocalChain::default(),
+            queued: BTreeMap::new(),
+        }
+    }
+}
+
+impl<S> From<LocalChain<S>> for QueuedBlocks<S> {
+    fn from(chain: LocalChain<S>) -> Self {
+        Self {
+            chain,
+            queued: BTreeMap::new(),
+        }
+    }
+}
+
+impl<S: ChainStore> QueuedBlocks<S> {
+    /// The underlying chain.
+    pub fn chain(&self) -> &LocalChain<S> {
+        &self.chain
+    }
+
+    /// The number of staged updates that have not yet connected.
+    pub fn queued_len(&self) -> usize {
+        self.queued.values().map(Vec::len).sum()
+    }
+
+    /// Apply `update`, or stage it if it does not connect.
+    ///
+    /// If the update connects, it is applied and the queue is replayed so that any staged update
+    /// whose gap has now been filled is applied too; the merged [`ChangeSet`] is returned. If the
+    /// update does not connect, it is staged and an empty [`ChangeSet`] is returned. An update that
+    /// would invalidate a finalized block is rejected with [`ApplyUpdateError::FinalizedReorg`].
+    pub fn queue_update(&mut self, update: LocalChain<S>) -> Result<ChangeSet, ApplyUpdateError> {
+        match self.chain.determine_changeset(&update) {
+            Ok(changeset) => {
+                self.chain.apply_changeset(changeset.clone());
+                let mut merged = changeset;
+                merged.extend(self.process_queue());
+                Ok(merged)
+            }
+            Err(ApplyUpdateError::NotConnected(_)) => {
+                if let Some(lowest) = update.heights().into_iter().next() {
+                    self.queued.entry(lowest).or_default().push(update);
+                }
+                Ok(ChangeSet::default())
+            }
+            Err(err @ ApplyUpdateError::FinalizedReorg(_)) => Err(err),
+        }
+    }
+
+    /// Repeatedly attempt to flush staged updates until no further progress is made.
+    ///
+    /// Returns the merged [`ChangeSet`] of everything that successfully applied. Staged updates
+    /// that sit entirely at or below the finalized tip, or that would invalidate a finalized block,
+    /// are pruned as permanently orphaned.
+    pub fn process_queue(&mut self) -> ChangeSet {
+        let mut merged = ChangeSet::new();
+        loop {
+            self.prune_orphaned();
+
+            let mut applied_any = false;
+            for key in self.queued.keys().cloned().collect::<Vec<_>>() {
+                let updates = match self.queued.remove(&key) {
+                    Some(updates) => updates,
+                    None => continue,
+                };
+                let mut still_queued = Vec::new();
+                for update in updates {
+                    match self.chain.determine_changeset(&update) {
+                        Ok(changeset) => {
+                            self.chain.apply_changeset(changeset.clone());
+                            merged.extend(changeset);
+                            applied_any = true;
+                        }
+                        Err(ApplyUpdateError::NotConnected(_)) => still_queued.push(update),
+                        // a staged update that reaches into the finalized segment is orphaned
+                        Err(ApplyUpdateError::FinalizedReorg(_)) => {}
+                    }
+                }
+                if !still_queued.is_empty() {
+                    self.queued.entry(key).or_default().extend(still_queued);
+                }
+            }
+            if !applied_any {
+                break;
+            }
+        }
+        merged
+    }
+
+    /// Drop staged updates that sit entirely at or below the finalized tip and can therefore never
+    /// contribute new blocks.
+    fn prune_orphaned(&mut self) {
+        let finalized_tip = match self.chain.finalized_tip() {
+            Some(height) => height,
+            None => return,
+        };
+        self.queued.retain(|_, updates| {
+            updates.retain(|update| {
+                update
+                    .heights()
+                    .into_iter()
+                    .next_back()
+                    .map_or(false, |highest| highest > finalized_tip)
+            });
+            !updates.is_empty()
+        });
     }
 }
 
@@ -201,3 +875,197 @@ impl core::fmt::Display for UpdateNotConnectedError {
 
 #[cfg(feature = "std")]
 impl std::error::Error for UpdateNotConnectedError {}
+
+/// Represents a failure to apply an update to [`LocalChain`].
+///
+/// This is the sibling of [`UpdateNotConnectedError`] that additionally carries the
+/// finalization-safety failure introduced by the finalized/non-finalized split.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ApplyUpdateError {
+    /// The update did not connect to the existing chain; see [`UpdateNotConnectedError`].
+    NotConnected(UpdateNotConnectedError),
+    /// The update attempts to invalidate a finalized block at the contained height, which is not
+    /// permitted.
+    FinalizedReorg(u32),
+}
+
+impl From<UpdateNotConnectedError> for ApplyUpdateError {
+    fn from(err: UpdateNotConnectedError) -> Self {
+        ApplyUpdateError::NotConnected(err)
+    }
+}
+
+impl core::fmt::Display for ApplyUpdateError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ApplyUpdateError::NotConnected(err) => err.fmt(f),
+            ApplyUpdateError::FinalizedReorg(height) => write!(
+                f,
+                "the update attempts to invalidate finalized block at height {}",
+                height
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ApplyUpdateError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bitcoin::hashes::Hash;
+
+    /// A distinct [`BlockHash`] for every `seed`, so test fixtures read unambiguously.
+    fn hash(seed: u8) -> BlockHash {
+        BlockHash::from_byte_array([seed; 32])
+    }
+
+    fn block(height: u32, seed: u8) -> BlockId {
+        BlockId {
+            height,
+            hash: hash(seed),
+        }
+    }
+
+    fn chain<B: IntoIterator<Item = BlockId>>(reorg_depth: u32, blocks: B) -> LocalChain {
+        let mut chain = LocalChain::from_blocks(blocks);
+        chain.reorg_depth = reorg_depth;
+        chain.finalized_tip = None;
+        chain.finalize();
+        chain
+    }
+
+    #[test]
+    fn reorg_at_finalized_boundary_is_rejected() {
+        // reorg_depth of 1 finalizes everything up to `tip - 1`, i.e. heights 0..=1.
+        let mut local = chain(1, [block(0, 0), block(1, 1), block(2, 2)]);
+        assert_eq!(local.finalized_tip(), Some(1));
+
+        // an update that rewrites the finalized block at height 1 must be refused
+        let update = chain(1, [block(0, 0), block(1, 9), block(2, 9)]);
+        assert_eq!(
+            local.apply_update(update),
+            Err(ApplyUpdateError::FinalizedReorg(1)),
+        );
+
+        // a reorg that only touches the non-finalized tip is still allowed
+        let update = chain(1, [block(1, 1), block(2, 9)]);
+        assert!(local.apply_update(update).is_ok());
+        assert_eq!(local.get_block(2), Some(block(2, 9)));
+    }
+
+    #[test]
+    fn finalized_shortcut_still_validates_static_block() {
+        let local = chain(1, [block(0, 0), block(1, 1), block(2, 2)]);
+        assert!(local.is_finalized(0));
+
+        // the queried block is finalized and on-chain, but the static block is not — ancestry must
+        // not be vouched for.
+        let off_chain_static = block(2, 9);
+        assert_eq!(
+            local.is_block_in_chain(block(0, 0), off_chain_static),
+            Ok(Some(false)),
+        );
+
+        // with an on-chain static block the finalized answer is `true`
+        assert_eq!(
+            local.is_block_in_chain(block(0, 0), block(2, 2)),
+            Ok(Some(true)),
+        );
+    }
+
+    #[test]
+    fn pruned_store_backs_a_local_chain() {
+        // a pruning store has no `Default`, so it must be constructable via `new`
+        let mut local = LocalChain::new(PrunedStore::new(2, 4));
+        local.reorg_depth = 2;
+
+        for height in 0..=8 {
+            let update = LocalChain::from_blocks((0..=height).map(|h| block(h, h as u8)));
+            local
+                .apply_update(update)
+                .expect("each extension connects");
+        }
+
+        // recent heights stay fully resolved...
+        assert_eq!(local.get_block(8), Some(block(8, 8)));
+        assert_eq!(local.get_block(7), Some(block(7, 7)));
+        // ...deep non-checkpoint heights are pruned, but checkpoints survive
+        assert_eq!(local.get_block(0), Some(block(0, 0)));
+        assert_eq!(local.get_block(4), Some(block(4, 4)));
+        assert_eq!(local.get_block(1), None);
+    }
+
+    #[test]
+    fn queue_flushes_a_gap_filled_update() {
+        let local = chain(u32::MAX, [block(0, 0), block(1, 1)]);
+        let mut queue = QueuedBlocks::from(local);
+
+        // an update for heights 3..=4 cannot connect yet (height 2 is missing) — it is staged
+        let disconnected = LocalChain::from_blocks([block(3, 3), block(4, 4)]);
+        let changeset = queue.queue_update(disconnected).expect("staging never errors");
+        assert!(changeset.is_empty());
+        assert_eq!(queue.queued_len(), 1);
+        assert_eq!(queue.chain().tip(), Some(block(1, 1)));
+
+        // the connecting update fills the gap, so the staged update flushes in the same call
+        let connecting = LocalChain::from_blocks([block(1, 1), block(2, 2), block(3, 3)]);
+        let merged = queue.queue_update(connecting).expect("connects");
+        assert_eq!(merged.get(&2), Some(&Some(hash(2))));
+        assert_eq!(merged.get(&3), Some(&Some(hash(3))));
+        assert_eq!(merged.get(&4), Some(&Some(hash(4))));
+        assert_eq!(queue.queued_len(), 0);
+        assert_eq!(queue.chain().tip(), Some(block(4, 4)));
+    }
+
+    #[test]
+    fn commit_best_chain_switches_to_higher_work_fork() {
+        let mut local = chain(u32::MAX, [block(0, 0), block(1, 1), block(2, 2)]);
+        local.set_active_score(10);
+
+        // a lower-scoring fork is ignored
+        local.add_candidate(Fork::from_blocks(1, 5, [block(2, 8), block(3, 8)]));
+        assert_eq!(local.commit_best_chain(), Ok(ChangeSet::default()));
+        assert_eq!(local.tip(), Some(block(2, 2)));
+        assert_eq!(local.active_score(), 10);
+
+        // a higher-scoring fork diverging at height 1 wins and rewrites the tip
+        local.add_candidate(Fork::from_blocks(1, 20, [block(2, 9), block(3, 9)]));
+        let changeset = local.commit_best_chain().expect("switch succeeds");
+        assert_eq!(changeset.get(&2), Some(&Some(hash(9))));
+        assert_eq!(changeset.get(&3), Some(&Some(hash(9))));
+        assert_eq!(local.tip(), Some(block(3, 9)));
+        assert_eq!(local.get_block(1), Some(block(1, 1)));
+        assert_eq!(local.active_score(), 20);
+        assert!(local.candidates().is_empty());
+    }
+
+    #[test]
+    fn prove_and_verify_round_trip() {
+        // exercise odd and even leaf counts, since odd counts trigger last-node duplication
+        for count in 1u32..=6 {
+            let local = chain(u32::MAX, (0..count).map(|h| block(h, h as u8)));
+            let root = local.header_tree_root().expect("non-empty chain has a root");
+
+            for height in 0..count {
+                let proof = local.prove_block(height).expect("block is in the chain");
+                assert!(
+                    verify_block_proof(root, &proof, block(height, height as u8)),
+                    "valid proof must verify (count={count}, height={height})",
+                );
+                // a proof for the wrong block must not verify
+                assert!(!verify_block_proof(root, &proof, block(height, 99)));
+            }
+        }
+    }
+
+    #[test]
+    fn proof_is_rejected_against_a_foreign_root() {
+        let local = chain(u32::MAX, (0..5).map(|h| block(h, h as u8)));
+        let other = chain(u32::MAX, (0..5).map(|h| block(h, (h as u8) + 100)));
+        let proof = local.prove_block(2).unwrap();
+        let foreign_root = other.header_tree_root().unwrap();
+        assert!(!verify_block_proof(foreign_root, &proof, block(2, 2)));
+    }
+}